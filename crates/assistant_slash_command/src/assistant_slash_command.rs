@@ -1,6 +1,9 @@
+pub mod command_tree;
+pub mod metrics;
+pub mod pipeline;
 mod slash_command_registry;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use futures::stream::{self, BoxStream};
 use futures::StreamExt;
 use gpui::{AnyElement, AppContext, ElementId, SharedString, Task, WeakView, WindowContext};
@@ -13,6 +16,8 @@ use std::{
 };
 use workspace::{ui::IconName, Workspace};
 
+pub use command_tree::CommandNode;
+
 pub fn init(cx: &mut AppContext) {
     SlashCommandRegistry::default_global(cx);
 }
@@ -60,6 +65,10 @@ pub struct ArgumentCompletion {
 
 pub type SlashCommandResult = Result<BoxStream<'static, Result<SlashCommandEvent>>>;
 
+/// A command implemented either via `complete_argument`/`run` or via [`Self::command_node`]. The
+/// registry wraps invocations of either in [`metrics::SlashCommandMetrics::record`], keyed by
+/// [`Self::name`], so slow or runaway commands can be identified without each command tracking
+/// its own timing.
 pub trait SlashCommand: 'static + Send + Sync {
     fn name(&self) -> String;
     fn label(&self, _cx: &AppContext) -> CodeLabel {
@@ -78,9 +87,28 @@ pub trait SlashCommand: 'static + Send + Sync {
     fn accepts_arguments(&self) -> bool {
         self.requires_argument()
     }
+    /// Returns the root of this command's argument grammar, as an alternative to hand-rolled
+    /// parsing in `complete_argument`. When present, the registry walks this tree against the
+    /// joined arguments to validate them, report [`command_tree::CommandSyntaxException`]s with a
+    /// precise cursor, and derive completions instead of calling `complete_argument` directly; the
+    /// resulting [`command_tree::CommandContext`] is then handed to [`Self::run`] as
+    /// `parsed_arguments`.
+    fn command_node(&self) -> Option<Arc<CommandNode>> {
+        None
+    }
+    /// An optional time/size budget for this invocation's event stream, enforced by the registry
+    /// against the `cancel` flag passed to [`Self::run`]/[`Self::run_piped`]. Returns `None` (no
+    /// budget) by default; override to cut off a command that runs too long or emits too much,
+    /// e.g. a `/fetch` streaming an unexpectedly large response.
+    fn budget(&self) -> Option<metrics::SlashCommandBudget> {
+        None
+    }
+    #[allow(clippy::too_many_arguments)]
     fn run(
         self: Arc<Self>,
         arguments: &[String],
+        // Populated from walking [`Self::command_node`]'s tree, when present.
+        parsed_arguments: Option<command_tree::CommandContext>,
         context_slash_command_output_sections: &[SlashCommandOutputSection<language::Anchor>],
         context_buffer: BufferSnapshot,
         workspace: WeakView<Workspace>,
@@ -90,8 +118,47 @@ pub trait SlashCommand: 'static + Send + Sync {
         // It may be that `LspAdapterDelegate` needs a more general name, or
         // perhaps another kind of delegate is needed here.
         delegate: Option<Arc<dyn LspAdapterDelegate>>,
+        // Set by the registry once this invocation's [`Self::budget`] is exceeded; long-running
+        // commands should check this and wind down instead of continuing to emit events.
+        cancel: Arc<AtomicBool>,
         cx: &mut WindowContext,
     ) -> Task<SlashCommandResult>;
+    /// Whether this command can appear on the right-hand side of a `|` and consume the
+    /// upstream command's event stream. Defaults to `false`, in which case `run_piped` is never
+    /// called and piping this command is rejected by the registry.
+    fn accepts_input(&self) -> bool {
+        false
+    }
+    /// Like [`Self::run`], but for a command invoked as the right-hand side of a pipeline
+    /// (`/diagnostics | /grep TODO`). `input` is the upstream command's event stream; downstream
+    /// events should be computed lazily from it as it arrives, to preserve incremental rendering.
+    /// The default implementation ignores `input` and defers to `run`, so implementing this is
+    /// opt-in and existing commands are unaffected.
+    #[allow(clippy::too_many_arguments)]
+    fn run_piped(
+        self: Arc<Self>,
+        input: BoxStream<'static, Result<SlashCommandEvent>>,
+        arguments: &[String],
+        parsed_arguments: Option<command_tree::CommandContext>,
+        context_slash_command_output_sections: &[SlashCommandOutputSection<language::Anchor>],
+        context_buffer: BufferSnapshot,
+        workspace: WeakView<Workspace>,
+        delegate: Option<Arc<dyn LspAdapterDelegate>>,
+        cancel: Arc<AtomicBool>,
+        cx: &mut WindowContext,
+    ) -> Task<SlashCommandResult> {
+        drop(input);
+        self.run(
+            arguments,
+            parsed_arguments,
+            context_slash_command_output_sections,
+            context_buffer,
+            workspace,
+            delegate,
+            cancel,
+            cx,
+        )
+    }
 }
 
 pub type RenderFoldPlaceholder = Arc<
@@ -100,12 +167,48 @@ pub type RenderFoldPlaceholder = Arc<
         + Fn(ElementId, Arc<dyn Fn(&mut WindowContext)>, &mut WindowContext) -> AnyElement,
 >;
 
-#[derive(Debug, PartialEq, Eq)]
+/// An embedded, non-text span of a [`SlashCommandOutput`]. The surrounding text holds a single
+/// [`OBJECT_REPLACEMENT_CHARACTER`] in place of the content, so that section ranges keep
+/// pointing at a real (if placeholder) span of `text`.
+pub const OBJECT_REPLACEMENT_CHARACTER: char = '\u{fffc}';
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlashCommandImageSource {
+    /// Base64-encoded image bytes, embedded directly in the output.
+    Data { data: String, mime_type: String },
+    /// A URL the client should fetch the image from.
+    Url(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SlashCommandContent {
     Text {
         text: String,
         run_commands_in_text: bool,
     },
+    Image {
+        source: SlashCommandImageSource,
+        alt: String,
+    },
+    Resource {
+        uri: String,
+        mime_type: String,
+        label: SharedString,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlashCommandDiagnosticSeverity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlashCommandDiagnostic {
+    pub severity: SlashCommandDiagnosticSeverity,
+    pub message: String,
+    pub source: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -119,6 +222,14 @@ pub enum SlashCommandEvent {
     EndSection {
         metadata: Option<serde_json::Value>,
     },
+    /// Reports a recoverable problem with part of the command's output (e.g. one failed item
+    /// out of many fetched). Unlike an `Err` yielded by the event stream, this does not abort the
+    /// command: it continues producing content after reporting the diagnostic.
+    Diagnostic {
+        severity: SlashCommandDiagnosticSeverity,
+        message: String,
+        source: Option<String>,
+    },
 }
 
 #[derive(Debug, Default, PartialEq, Clone)]
@@ -126,6 +237,7 @@ pub struct SlashCommandOutput {
     pub text: String,
     pub sections: Vec<SlashCommandOutputSection<usize>>,
     pub run_commands_in_text: bool,
+    pub diagnostics: Vec<SlashCommandDiagnostic>,
 }
 
 impl SlashCommandOutput {
@@ -151,14 +263,20 @@ impl SlashCommandOutput {
                 label: section.label,
                 metadata: section.metadata.clone(),
             }));
-            events.push(Ok(SlashCommandEvent::Content(SlashCommandContent::Text {
-                text: self
-                    .text
-                    .get(section.range.start..section.range.end)
-                    .unwrap_or_default()
-                    .to_string(),
-                run_commands_in_text: self.run_commands_in_text,
-            })));
+            events.push(Ok(SlashCommandEvent::Content(
+                if let Some(content) = section.content.clone() {
+                    content
+                } else {
+                    SlashCommandContent::Text {
+                        text: self
+                            .text
+                            .get(section.range.start..section.range.end)
+                            .unwrap_or_default()
+                            .to_string(),
+                        run_commands_in_text: self.run_commands_in_text,
+                    }
+                },
+            )));
             events.push(Ok(SlashCommandEvent::EndSection {
                 metadata: section.metadata,
             }));
@@ -173,6 +291,14 @@ impl SlashCommandOutput {
             })));
         }
 
+        for diagnostic in self.diagnostics {
+            events.push(Ok(SlashCommandEvent::Diagnostic {
+                severity: diagnostic.severity,
+                message: diagnostic.message,
+                source: diagnostic.source,
+            }));
+        }
+
         stream::iter(events).boxed()
     }
 
@@ -199,12 +325,23 @@ impl SlashCommandOutput {
                         icon,
                         label,
                         metadata,
+                        content: None,
                     });
                 }
                 SlashCommandEvent::Content(SlashCommandContent::Text {
                     text,
                     run_commands_in_text,
                 }) => {
+                    if let Some(section) = current_section.as_ref() {
+                        if section.content.is_some() {
+                            bail!(
+                                "received text content for a section that already has \
+                                 image/resource content; a section may carry only one \
+                                 content payload"
+                            );
+                        }
+                    }
+
                     output.text.push_str(&text);
                     output.run_commands_in_text = run_commands_in_text;
 
@@ -212,12 +349,49 @@ impl SlashCommandOutput {
                         section.range.end = output.text.len();
                     }
                 }
+                content @ SlashCommandEvent::Content(
+                    SlashCommandContent::Image { .. } | SlashCommandContent::Resource { .. },
+                ) => {
+                    let SlashCommandEvent::Content(content) = content else {
+                        unreachable!()
+                    };
+                    // Image/Resource content is only meaningful as a section's `content`, so
+                    // there must be an open section to attach it to, or the placeholder character
+                    // we're about to push would point at nothing and the content would be lost.
+                    let Some(section) = current_section.as_mut() else {
+                        bail!(
+                            "received {content:?} content with no open section; \
+                             image/resource content must be wrapped in StartSection/EndSection"
+                        );
+                    };
+                    if section.content.is_some() {
+                        bail!(
+                            "received {content:?} content for a section that already has \
+                             content; a section may carry only one image/resource"
+                        );
+                    }
+
+                    output.text.push(OBJECT_REPLACEMENT_CHARACTER);
+                    section.range.end = output.text.len();
+                    section.content = Some(content);
+                }
                 SlashCommandEvent::EndSection { metadata } => {
                     if let Some(mut section) = current_section.take() {
                         section.metadata = metadata;
                         output.sections.push(section);
                     }
                 }
+                SlashCommandEvent::Diagnostic {
+                    severity,
+                    message,
+                    source,
+                } => {
+                    output.diagnostics.push(SlashCommandDiagnostic {
+                        severity,
+                        message,
+                        source,
+                    });
+                }
             }
         }
 
@@ -235,6 +409,9 @@ pub struct SlashCommandOutputSection<T> {
     pub icon: IconName,
     pub label: SharedString,
     pub metadata: Option<serde_json::Value>,
+    /// When set, this section embeds non-text content (an image or resource) rather than a byte
+    /// range of `text`; `range` then spans the placeholder character standing in for it.
+    pub content: Option<SlashCommandContent>,
 }
 
 impl SlashCommandOutputSection<language::Anchor> {
@@ -263,8 +440,10 @@ mod tests {
                     icon: IconName::Code,
                     label: "Section 1".into(),
                     metadata: None,
+                    content: None,
                 }],
                 run_commands_in_text: false,
+                diagnostics: Vec::new(),
             };
 
             let events = output.clone().to_event_stream().collect::<Vec<_>>().await;
@@ -308,15 +487,18 @@ mod tests {
                         icon: IconName::Check,
                         label: "Fruit".into(),
                         metadata: None,
+                        content: None,
                     },
                     SlashCommandOutputSection {
                         range: 15..22,
                         icon: IconName::Check,
                         label: "Fruit".into(),
                         metadata: None,
+                        content: None,
                     },
                 ],
                 run_commands_in_text: false,
+                diagnostics: Vec::new(),
             };
 
             let events = output.clone().to_event_stream().collect::<Vec<_>>().await;
@@ -374,27 +556,32 @@ mod tests {
                         icon: IconName::FileCode,
                         label: "Section 1".into(),
                         metadata: Some(json!({ "a": true })),
+                        content: None,
                     },
                     SlashCommandOutputSection {
                         range: 7..13,
                         icon: IconName::FileDoc,
                         label: "Section 2".into(),
                         metadata: Some(json!({ "b": true })),
+                        content: None,
                     },
                     SlashCommandOutputSection {
                         range: 14..20,
                         icon: IconName::FileGit,
                         label: "Section 3".into(),
                         metadata: Some(json!({ "c": true })),
+                        content: None,
                     },
                     SlashCommandOutputSection {
                         range: 21..27,
                         icon: IconName::FileToml,
                         label: "Section 4".into(),
                         metadata: Some(json!({ "d": true })),
+                        content: None,
                     },
                 ],
                 run_commands_in_text: false,
+                diagnostics: Vec::new(),
             };
 
             let events = output.clone().to_event_stream().collect::<Vec<_>>().await;
@@ -480,5 +667,179 @@ mod tests {
 
             assert_eq!(new_output, output);
         }
+
+        // Test output mixing text sections with an embedded image.
+        {
+            let text = format!("Before\n{OBJECT_REPLACEMENT_CHARACTER}\nAfter\n");
+            let image_start = "Before\n".len();
+            let image_end = image_start + OBJECT_REPLACEMENT_CHARACTER.len_utf8();
+            let output = SlashCommandOutput {
+                text,
+                sections: vec![SlashCommandOutputSection {
+                    range: image_start..image_end,
+                    icon: IconName::Image,
+                    label: "Screenshot".into(),
+                    metadata: None,
+                    content: Some(SlashCommandContent::Image {
+                        source: SlashCommandImageSource::Data {
+                            data: "base64data".into(),
+                            mime_type: "image/png".into(),
+                        },
+                        alt: "a screenshot".into(),
+                    }),
+                }],
+                run_commands_in_text: false,
+                diagnostics: Vec::new(),
+            };
+
+            let events = output.clone().to_event_stream().collect::<Vec<_>>().await;
+            let events = events
+                .into_iter()
+                .filter_map(|event| event.ok())
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                events,
+                vec![
+                    SlashCommandEvent::Content(SlashCommandContent::Text {
+                        text: "Before\n".into(),
+                        run_commands_in_text: false
+                    }),
+                    SlashCommandEvent::StartSection {
+                        icon: IconName::Image,
+                        label: "Screenshot".into(),
+                        metadata: None
+                    },
+                    SlashCommandEvent::Content(SlashCommandContent::Image {
+                        source: SlashCommandImageSource::Data {
+                            data: "base64data".into(),
+                            mime_type: "image/png".into(),
+                        },
+                        alt: "a screenshot".into(),
+                    }),
+                    SlashCommandEvent::EndSection { metadata: None },
+                    SlashCommandEvent::Content(SlashCommandContent::Text {
+                        text: "\nAfter\n".into(),
+                        run_commands_in_text: false
+                    }),
+                ]
+            );
+
+            let new_output =
+                SlashCommandOutput::from_event_stream(output.clone().to_event_stream())
+                    .await
+                    .unwrap();
+
+            assert_eq!(new_output, output);
+        }
+
+        // Test output with a mid-stream diagnostic alongside regular content.
+        {
+            let text = "Fetched 1 of 2 pages\n".to_string();
+            let output = SlashCommandOutput {
+                text,
+                sections: Vec::new(),
+                run_commands_in_text: false,
+                diagnostics: vec![SlashCommandDiagnostic {
+                    severity: SlashCommandDiagnosticSeverity::Warning,
+                    message: "Failed to fetch page 2: timed out".into(),
+                    source: Some("fetch".into()),
+                }],
+            };
+
+            let events = output.clone().to_event_stream().collect::<Vec<_>>().await;
+            let events = events
+                .into_iter()
+                .filter_map(|event| event.ok())
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                events,
+                vec![
+                    SlashCommandEvent::Content(SlashCommandContent::Text {
+                        text: "Fetched 1 of 2 pages\n".into(),
+                        run_commands_in_text: false
+                    }),
+                    SlashCommandEvent::Diagnostic {
+                        severity: SlashCommandDiagnosticSeverity::Warning,
+                        message: "Failed to fetch page 2: timed out".into(),
+                        source: Some("fetch".into()),
+                    },
+                ]
+            );
+
+            let new_output =
+                SlashCommandOutput::from_event_stream(output.clone().to_event_stream())
+                    .await
+                    .unwrap();
+
+            assert_eq!(new_output, output);
+        }
+    }
+
+    #[gpui::test]
+    async fn test_from_event_stream_rejects_unsectioned_image() {
+        let events: Vec<Result<SlashCommandEvent>> = vec![Ok(SlashCommandEvent::Content(
+            SlashCommandContent::Image {
+                source: SlashCommandImageSource::Url("https://example.com/a.png".into()),
+                alt: "an image".into(),
+            },
+        ))];
+
+        let error = SlashCommandOutput::from_event_stream(stream::iter(events).boxed())
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("no open section"));
+    }
+
+    #[gpui::test]
+    async fn test_from_event_stream_rejects_duplicate_section_content() {
+        let events: Vec<Result<SlashCommandEvent>> = vec![
+            Ok(SlashCommandEvent::StartSection {
+                icon: IconName::Code,
+                label: "section".into(),
+                metadata: None,
+            }),
+            Ok(SlashCommandEvent::Content(SlashCommandContent::Image {
+                source: SlashCommandImageSource::Url("https://example.com/a.png".into()),
+                alt: "an image".into(),
+            })),
+            Ok(SlashCommandEvent::Content(SlashCommandContent::Image {
+                source: SlashCommandImageSource::Url("https://example.com/b.png".into()),
+                alt: "another image".into(),
+            })),
+        ];
+
+        let error = SlashCommandOutput::from_event_stream(stream::iter(events).boxed())
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("already has"));
+    }
+
+    #[gpui::test]
+    async fn test_from_event_stream_rejects_text_after_section_image() {
+        let events: Vec<Result<SlashCommandEvent>> = vec![
+            Ok(SlashCommandEvent::StartSection {
+                icon: IconName::Code,
+                label: "section".into(),
+                metadata: None,
+            }),
+            Ok(SlashCommandEvent::Content(SlashCommandContent::Image {
+                source: SlashCommandImageSource::Url("https://example.com/a.png".into()),
+                alt: "an image".into(),
+            })),
+            Ok(SlashCommandEvent::Content(SlashCommandContent::Text {
+                text: "trailing text".into(),
+                run_commands_in_text: false,
+            })),
+        ];
+
+        let error = SlashCommandOutput::from_event_stream(stream::iter(events).boxed())
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("already has"));
     }
 }
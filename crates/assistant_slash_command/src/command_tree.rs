@@ -0,0 +1,551 @@
+//! A declarative, Brigadier-style argument grammar that a [`crate::SlashCommand`] can expose
+//! as an alternative to hand-rolled `complete_argument`/`run` parsing.
+//!
+//! A command builds a tree of [`CommandNode`]s out of [`CommandNode::literal`] (fixed
+//! sub-keywords, e.g. `project` in `/project add`) and [`CommandNode::argument`] (a typed
+//! value parsed by an [`ArgumentType`]). [`walk`] consumes the tree token-by-token against the
+//! raw command line, populating a [`CommandContext`] with the parsed values, while [`complete`]
+//! asks whichever node the cursor lands on for its suggestions.
+
+use std::{any::Any, collections::HashMap, fmt, sync::Arc};
+
+use crate::ArgumentCompletion;
+
+/// A parse failure at a specific offset into the command line, so the editor can underline the
+/// exact offending span instead of just failing the command outright.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSyntaxException {
+    pub message: String,
+    pub cursor: usize,
+}
+
+impl fmt::Display for CommandSyntaxException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at position {})", self.message, self.cursor)
+    }
+}
+
+impl std::error::Error for CommandSyntaxException {}
+
+/// A cursor-tracking view over the unparsed remainder of a command line.
+#[derive(Debug, Clone)]
+pub struct ArgumentReader<'a> {
+    input: &'a str,
+    cursor: usize,
+}
+
+impl<'a> ArgumentReader<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input, cursor: 0 }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn remaining(&self) -> &'a str {
+        &self.input[self.cursor.min(self.input.len())..]
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.remaining().is_empty()
+    }
+
+    pub fn skip_whitespace(&mut self) {
+        let skipped = self.remaining().len() - self.remaining().trim_start().len();
+        self.cursor += skipped;
+    }
+
+    /// Reads up to the next whitespace, without consuming it.
+    pub fn read_unquoted_string(&mut self) -> &'a str {
+        let remaining = self.remaining();
+        let len = remaining
+            .find(char::is_whitespace)
+            .unwrap_or(remaining.len());
+        self.cursor += len;
+        &remaining[..len]
+    }
+
+    /// Reads the rest of the input, trailing whitespace included.
+    pub fn read_remaining(&mut self) -> &'a str {
+        let remaining = self.remaining();
+        self.cursor += remaining.len();
+        remaining
+    }
+
+    pub fn expect_literal(&mut self, literal: &str) -> Result<(), CommandSyntaxException> {
+        self.skip_whitespace();
+        let start = self.cursor;
+        let token = self.read_unquoted_string();
+        if token == literal {
+            Ok(())
+        } else {
+            self.cursor = start;
+            Err(CommandSyntaxException {
+                message: format!("Expected literal `{literal}`"),
+                cursor: start,
+            })
+        }
+    }
+}
+
+/// Holds the typed values parsed out of a command line, keyed by argument name.
+#[derive(Default)]
+pub struct CommandContext {
+    arguments: HashMap<String, Box<dyn Any + Send + Sync>>,
+}
+
+impl fmt::Debug for CommandContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandContext")
+            .field("arguments", &self.arguments.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl CommandContext {
+    pub fn insert<T: Send + Sync + 'static>(&mut self, name: &str, value: T) {
+        self.arguments.insert(name.to_string(), Box::new(value));
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self, name: &str) -> Option<&T> {
+        self.arguments
+            .get(name)
+            .and_then(|value| value.downcast_ref())
+    }
+}
+
+/// A single kind of argument value (`Integer`, `Enum`, `Path`, `GreedyString`, ...). Implementors
+/// know how to both parse their value out of an [`ArgumentReader`] and suggest completions for a
+/// partially-typed token.
+pub trait ArgumentType: 'static + Send + Sync {
+    fn type_name(&self) -> &'static str;
+
+    /// Parses this argument's value out of `reader`, advancing its cursor past the consumed
+    /// tokens, and stores it into `context` under `name`.
+    fn parse(
+        &self,
+        reader: &mut ArgumentReader,
+        context: &mut CommandContext,
+        name: &str,
+    ) -> Result<(), CommandSyntaxException>;
+
+    /// Returns completions for the given partial token.
+    fn suggestions(&self, partial: &str) -> Vec<ArgumentCompletion>;
+}
+
+/// Parses a base-10 signed integer.
+pub struct Integer;
+
+impl ArgumentType for Integer {
+    fn type_name(&self) -> &'static str {
+        "integer"
+    }
+
+    fn parse(
+        &self,
+        reader: &mut ArgumentReader,
+        context: &mut CommandContext,
+        name: &str,
+    ) -> Result<(), CommandSyntaxException> {
+        reader.skip_whitespace();
+        let start = reader.cursor();
+        let token = reader.read_unquoted_string();
+        match token.parse::<i64>() {
+            Ok(value) => {
+                context.insert(name, value);
+                Ok(())
+            }
+            Err(_) => {
+                reader.cursor = start;
+                Err(CommandSyntaxException {
+                    message: format!("Expected an integer, got `{token}`"),
+                    cursor: start,
+                })
+            }
+        }
+    }
+
+    fn suggestions(&self, _partial: &str) -> Vec<ArgumentCompletion> {
+        Vec::new()
+    }
+}
+
+/// Parses the rest of the line as a single string, whitespace included.
+pub struct GreedyString;
+
+impl ArgumentType for GreedyString {
+    fn type_name(&self) -> &'static str {
+        "greedy_string"
+    }
+
+    fn parse(
+        &self,
+        reader: &mut ArgumentReader,
+        context: &mut CommandContext,
+        name: &str,
+    ) -> Result<(), CommandSyntaxException> {
+        reader.skip_whitespace();
+        context.insert(name, reader.read_remaining().to_string());
+        Ok(())
+    }
+
+    fn suggestions(&self, _partial: &str) -> Vec<ArgumentCompletion> {
+        Vec::new()
+    }
+}
+
+/// Parses a single whitespace-delimited word out of a fixed set of accepted values.
+pub struct Enum {
+    pub values: Vec<String>,
+}
+
+impl Enum {
+    pub fn new(values: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl ArgumentType for Enum {
+    fn type_name(&self) -> &'static str {
+        "enum"
+    }
+
+    fn parse(
+        &self,
+        reader: &mut ArgumentReader,
+        context: &mut CommandContext,
+        name: &str,
+    ) -> Result<(), CommandSyntaxException> {
+        reader.skip_whitespace();
+        let start = reader.cursor();
+        let token = reader.read_unquoted_string();
+        if self.values.iter().any(|value| value == token) {
+            context.insert(name, token.to_string());
+            Ok(())
+        } else {
+            reader.cursor = start;
+            Err(CommandSyntaxException {
+                message: format!("Expected one of {:?}, got `{token}`", self.values),
+                cursor: start,
+            })
+        }
+    }
+
+    fn suggestions(&self, partial: &str) -> Vec<ArgumentCompletion> {
+        self.values
+            .iter()
+            .filter(|value| value.starts_with(partial))
+            .map(|value| ArgumentCompletion {
+                label: language::CodeLabel::plain(value.clone(), None),
+                new_text: value.clone(),
+                after_completion: crate::AfterCompletion::Continue,
+                replace_previous_arguments: false,
+            })
+            .collect()
+    }
+}
+
+/// Overrides `after_completion` to [`crate::AfterCompletion::Run`] when `is_leaf` is set, since a
+/// completion that lands on a node with no children has nothing left to compose.
+fn with_leaf_after_completion(
+    mut completions: Vec<ArgumentCompletion>,
+    is_leaf: bool,
+) -> Vec<ArgumentCompletion> {
+    if is_leaf {
+        for completion in &mut completions {
+            completion.after_completion = crate::AfterCompletion::Run;
+        }
+    }
+    completions
+}
+
+/// Parses a single whitespace-delimited path-like token. Unlike [`Enum`], any token is accepted;
+/// actual filesystem resolution is left to the command's `run`.
+pub struct Path;
+
+impl ArgumentType for Path {
+    fn type_name(&self) -> &'static str {
+        "path"
+    }
+
+    fn parse(
+        &self,
+        reader: &mut ArgumentReader,
+        context: &mut CommandContext,
+        name: &str,
+    ) -> Result<(), CommandSyntaxException> {
+        reader.skip_whitespace();
+        let start = reader.cursor();
+        let token = reader.read_unquoted_string();
+        if token.is_empty() {
+            reader.cursor = start;
+            return Err(CommandSyntaxException {
+                message: "Expected a path".into(),
+                cursor: start,
+            });
+        }
+        context.insert(name, token.to_string());
+        Ok(())
+    }
+
+    fn suggestions(&self, _partial: &str) -> Vec<ArgumentCompletion> {
+        Vec::new()
+    }
+}
+
+enum NodeKind {
+    Literal(String),
+    Argument(String, Arc<dyn ArgumentType>),
+}
+
+/// A single node in a command's argument tree. See the module documentation for how trees are
+/// assembled and walked.
+pub struct CommandNode {
+    kind: NodeKind,
+    children: Vec<Arc<CommandNode>>,
+    /// When set, matching continues against this node's children instead of `self.children`,
+    /// letting aliases and forks reuse another subtree without duplicating it.
+    redirect: Option<Arc<CommandNode>>,
+}
+
+impl CommandNode {
+    pub fn literal(name: impl Into<String>) -> CommandNodeBuilder {
+        CommandNodeBuilder {
+            kind: NodeKind::Literal(name.into()),
+            children: Vec::new(),
+            redirect: None,
+        }
+    }
+
+    pub fn argument(
+        name: impl Into<String>,
+        argument_type: impl ArgumentType,
+    ) -> CommandNodeBuilder {
+        CommandNodeBuilder {
+            kind: NodeKind::Argument(name.into(), Arc::new(argument_type)),
+            children: Vec::new(),
+            redirect: None,
+        }
+    }
+
+    fn children(&self) -> &[Arc<CommandNode>] {
+        self.redirect
+            .as_deref()
+            .map_or(self.children.as_slice(), |target| &target.children)
+    }
+}
+
+pub struct CommandNodeBuilder {
+    kind: NodeKind,
+    children: Vec<Arc<CommandNode>>,
+    redirect: Option<Arc<CommandNode>>,
+}
+
+impl CommandNodeBuilder {
+    pub fn then(mut self, child: impl Into<Arc<CommandNode>>) -> Self {
+        self.children.push(child.into());
+        self
+    }
+
+    /// Forks this node onto `target`'s subtree, so this node's children are effectively
+    /// `target`'s children. Useful for aliasing one literal onto another command's arguments.
+    pub fn redirect(mut self, target: Arc<CommandNode>) -> Self {
+        self.redirect = Some(target);
+        self
+    }
+
+    pub fn build(self) -> Arc<CommandNode> {
+        Arc::new(CommandNode {
+            kind: self.kind,
+            children: self.children,
+            redirect: self.redirect,
+        })
+    }
+}
+
+impl From<CommandNodeBuilder> for Arc<CommandNode> {
+    fn from(builder: CommandNodeBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Walks `root` against `input`, consuming literals by exact match and invoking each
+/// [`ArgumentType::parse`] along the way, returning the populated [`CommandContext`] once the
+/// input is exhausted.
+pub fn walk(root: &CommandNode, input: &str) -> Result<CommandContext, CommandSyntaxException> {
+    let mut reader = ArgumentReader::new(input);
+    let mut context = CommandContext::default();
+    walk_node(root, &mut reader, &mut context)?;
+    Ok(context)
+}
+
+fn walk_node(
+    node: &CommandNode,
+    reader: &mut ArgumentReader,
+    context: &mut CommandContext,
+) -> Result<(), CommandSyntaxException> {
+    match &node.kind {
+        NodeKind::Literal(name) => reader.expect_literal(name)?,
+        NodeKind::Argument(name, argument_type) => argument_type.parse(reader, context, name)?,
+    }
+
+    reader.skip_whitespace();
+    if reader.is_at_end() {
+        return Ok(());
+    }
+
+    let children = node.children();
+    if children.is_empty() {
+        return Err(CommandSyntaxException {
+            message: "Unexpected trailing input".into(),
+            cursor: reader.cursor(),
+        });
+    }
+
+    let mut last_error = None;
+    for child in children {
+        let mut child_reader = reader.clone();
+        let mut child_context = CommandContext::default();
+        match walk_node(child, &mut child_reader, &mut child_context) {
+            Ok(()) => {
+                context.arguments.extend(child_context.arguments);
+                *reader = child_reader;
+                return Ok(());
+            }
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    Err(last_error.unwrap_or(CommandSyntaxException {
+        message: "No matching subcommand".into(),
+        cursor: reader.cursor(),
+    }))
+}
+
+/// Returns completions for whichever node the cursor lands on at the end of `input`.
+pub fn complete(root: &CommandNode, input: &str) -> Vec<ArgumentCompletion> {
+    let mut reader = ArgumentReader::new(input);
+    let mut context = CommandContext::default();
+    complete_node(root, &mut reader, &mut context)
+}
+
+fn complete_node(
+    node: &CommandNode,
+    reader: &mut ArgumentReader,
+    context: &mut CommandContext,
+) -> Vec<ArgumentCompletion> {
+    reader.skip_whitespace();
+    let start = reader.cursor();
+
+    match &node.kind {
+        NodeKind::Literal(name) => {
+            let token = reader.read_unquoted_string();
+            if reader.is_at_end() {
+                return if name.starts_with(token) {
+                    with_leaf_after_completion(
+                        vec![ArgumentCompletion {
+                            label: language::CodeLabel::plain(name.clone(), None),
+                            new_text: name.clone(),
+                            after_completion: crate::AfterCompletion::Continue,
+                            replace_previous_arguments: false,
+                        }],
+                        node.children().is_empty(),
+                    )
+                } else {
+                    Vec::new()
+                };
+            }
+            if token != name {
+                reader.cursor = start;
+                return Vec::new();
+            }
+        }
+        NodeKind::Argument(name, argument_type) => {
+            if reader.remaining().find(char::is_whitespace).is_none() {
+                return with_leaf_after_completion(
+                    argument_type.suggestions(reader.remaining()),
+                    node.children().is_empty(),
+                );
+            }
+            if argument_type.parse(reader, context, name).is_err() {
+                return Vec::new();
+            }
+        }
+    }
+
+    reader.skip_whitespace();
+    node.children()
+        .iter()
+        .flat_map(|child| complete_node(child, &mut reader.clone(), &mut CommandContext::default()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_literal_and_argument() {
+        let tree = CommandNode::literal("project")
+            .then(CommandNode::literal("add").then(CommandNode::argument("count", Integer)))
+            .build();
+
+        let context = walk(&tree, "project add 3").unwrap();
+        assert_eq!(context.get::<i64>("count"), Some(&3));
+    }
+
+    #[test]
+    fn reports_cursor_on_parse_failure() {
+        let tree = CommandNode::literal("project")
+            .then(CommandNode::argument("count", Integer))
+            .build();
+
+        let error = walk(&tree, "project NaN").unwrap_err();
+        assert_eq!(error.cursor, "project ".len());
+    }
+
+    #[test]
+    fn redirect_reuses_target_subtree() {
+        let target = CommandNode::literal("target")
+            .then(CommandNode::argument("count", Integer))
+            .build();
+        let tree = CommandNode::literal("alias").redirect(target).build();
+
+        let context = walk(&tree, "alias 7").unwrap();
+        assert_eq!(context.get::<i64>("count"), Some(&7));
+    }
+
+    #[test]
+    fn suggests_enum_values_for_partial_token() {
+        let argument_type = Enum::new(["add", "remove"]);
+        let suggestions = argument_type.suggestions("a");
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].new_text, "add");
+    }
+
+    #[test]
+    fn leaf_completions_run_on_accept() {
+        let tree = CommandNode::literal("project")
+            .then(CommandNode::literal("add").then(CommandNode::argument(
+                "kind",
+                Enum::new(["foo", "bar"]),
+            )))
+            .build();
+
+        // `kind` has no children, so a completion for it should run on accept.
+        let completions = complete(&tree, "project add f");
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].after_completion, crate::AfterCompletion::Run);
+
+        // `project` has children, so completing it should only continue composing.
+        let completions = complete(&tree, "project");
+        assert_eq!(completions.len(), 1);
+        assert_eq!(
+            completions[0].after_completion,
+            crate::AfterCompletion::Continue
+        );
+    }
+}
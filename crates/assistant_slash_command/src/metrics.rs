@@ -0,0 +1,266 @@
+//! Lightweight per-command execution metrics, in the spirit of an LSP server's per-request
+//! `Performance` timings. `SlashCommandRegistry` wraps every `complete_argument` and `run`
+//! invocation with [`SlashCommandMetrics::record`], keeping a bounded ring buffer of recent
+//! samples per command plus running averages, so a `/metrics` diagnostic command (or a slow/
+//! runaway `/fetch`) can be inspected without instrumenting each command individually.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Which half of a command invocation a [`SlashCommandSample`] was recorded for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SlashCommandPhase {
+    Complete,
+    Run,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlashCommandSample {
+    pub phase: SlashCommandPhase,
+    pub duration: Duration,
+    pub event_count: usize,
+    pub byte_len: usize,
+}
+
+/// Running averages over all samples seen for a command, not just the retained ring buffer, so
+/// long-lived commands don't lose their history as old samples are evicted. Samples of both
+/// phases are averaged together; the averages are not split by [`SlashCommandPhase`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlashCommandAverages {
+    pub sample_count: usize,
+    pub average_duration: Duration,
+    pub average_event_count: f64,
+    pub average_byte_len: f64,
+}
+
+#[derive(Default)]
+struct CommandMetrics {
+    recent: VecDeque<SlashCommandSample>,
+    sample_count: usize,
+    total_duration: Duration,
+    total_event_count: usize,
+    total_byte_len: usize,
+}
+
+impl CommandMetrics {
+    fn record(&mut self, sample: SlashCommandSample, max_samples: usize) {
+        self.sample_count += 1;
+        self.total_duration += sample.duration;
+        self.total_event_count += sample.event_count;
+        self.total_byte_len += sample.byte_len;
+
+        self.recent.push_back(sample);
+        while self.recent.len() > max_samples {
+            self.recent.pop_front();
+        }
+    }
+
+    fn averages(&self) -> SlashCommandAverages {
+        let sample_count = self.sample_count.max(1);
+        SlashCommandAverages {
+            sample_count: self.sample_count,
+            average_duration: self.total_duration / sample_count as u32,
+            average_event_count: self.total_event_count as f64 / sample_count as f64,
+            average_byte_len: self.total_byte_len as f64 / sample_count as f64,
+        }
+    }
+}
+
+/// A ring-buffered store of recent [`SlashCommandSample`]s, keyed by command name.
+pub struct SlashCommandMetrics {
+    max_samples_per_command: usize,
+    by_command: Mutex<HashMap<String, CommandMetrics>>,
+}
+
+impl Default for SlashCommandMetrics {
+    fn default() -> Self {
+        Self::new(50)
+    }
+}
+
+impl SlashCommandMetrics {
+    pub fn new(max_samples_per_command: usize) -> Self {
+        Self {
+            max_samples_per_command,
+            by_command: Mutex::new(HashMap::default()),
+        }
+    }
+
+    pub fn record(
+        &self,
+        command_name: &str,
+        phase: SlashCommandPhase,
+        duration: Duration,
+        event_count: usize,
+        byte_len: usize,
+    ) {
+        let mut by_command = self.by_command.lock().unwrap();
+        by_command
+            .entry(command_name.to_string())
+            .or_default()
+            .record(
+                SlashCommandSample {
+                    phase,
+                    duration,
+                    event_count,
+                    byte_len,
+                },
+                self.max_samples_per_command,
+            );
+    }
+
+    /// Times `f`, recording a sample for `command_name`/`phase` with the elapsed duration and the
+    /// event count/byte length `f` reports about its own output.
+    pub fn time<T>(
+        &self,
+        command_name: &str,
+        phase: SlashCommandPhase,
+        f: impl FnOnce() -> (T, usize, usize),
+    ) -> T {
+        let start = Instant::now();
+        let (result, event_count, byte_len) = f();
+        self.record_since(command_name, phase, start, event_count, byte_len);
+        result
+    }
+
+    /// Records a sample for `command_name`/`phase` whose duration is `start.elapsed()`, for
+    /// callers (an async task, a stream wrapper) that can't hand [`Self::time`] a plain closure
+    /// because the timed work spans an `.await` or multiple calls.
+    pub fn record_since(
+        &self,
+        command_name: &str,
+        phase: SlashCommandPhase,
+        start: Instant,
+        event_count: usize,
+        byte_len: usize,
+    ) {
+        self.record(command_name, phase, start.elapsed(), event_count, byte_len);
+    }
+
+    pub fn recent_samples(&self, command_name: &str) -> Vec<SlashCommandSample> {
+        self.by_command
+            .lock()
+            .unwrap()
+            .get(command_name)
+            .map(|metrics| metrics.recent.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn averages(&self, command_name: &str) -> Option<SlashCommandAverages> {
+        self.by_command
+            .lock()
+            .unwrap()
+            .get(command_name)
+            .map(CommandMetrics::averages)
+    }
+
+    pub fn command_names(&self) -> Vec<String> {
+        self.by_command.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+/// Cancels a command's `cancel: Arc<AtomicBool>` once it has run longer than `timeout` or emitted
+/// more than `max_bytes`, so a runaway `/fetch` that streams megabytes can be cut off without the
+/// command needing to track its own budget. Both limits are checked only when
+/// [`Self::record_bytes`] is called, so a command that stops emitting output is not cancelled by
+/// its timeout.
+pub struct SlashCommandBudget {
+    deadline: Instant,
+    max_bytes: Option<usize>,
+    emitted_bytes: AtomicUsize,
+}
+
+impl SlashCommandBudget {
+    pub fn new(timeout: Duration, max_bytes: Option<usize>) -> Self {
+        Self {
+            deadline: Instant::now() + timeout,
+            max_bytes,
+            emitted_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Accounts for `len` additional emitted bytes, cancelling via `cancel` if this budget is now
+    /// exceeded. Returns whether the budget was exceeded.
+    pub fn record_bytes(&self, len: usize, cancel: &AtomicBool) -> bool {
+        let total = self.emitted_bytes.fetch_add(len, Ordering::SeqCst) + len;
+        let exceeded = Instant::now() >= self.deadline
+            || self.max_bytes.is_some_and(|max_bytes| total > max_bytes);
+        if exceeded {
+            cancel.store(true, Ordering::SeqCst);
+        }
+        exceeded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_samples_and_averages() {
+        let metrics = SlashCommandMetrics::new(2);
+
+        metrics.record(
+            "fetch",
+            SlashCommandPhase::Run,
+            Duration::from_millis(10),
+            3,
+            100,
+        );
+        metrics.record(
+            "fetch",
+            SlashCommandPhase::Run,
+            Duration::from_millis(30),
+            5,
+            300,
+        );
+
+        let averages = metrics.averages("fetch").unwrap();
+        assert_eq!(averages.sample_count, 2);
+        assert_eq!(averages.average_duration, Duration::from_millis(20));
+        assert_eq!(averages.average_event_count, 4.0);
+        assert_eq!(averages.average_byte_len, 200.0);
+    }
+
+    #[test]
+    fn ring_buffer_evicts_oldest_sample() {
+        let metrics = SlashCommandMetrics::new(2);
+
+        for millis in [10, 20, 30] {
+            metrics.record(
+                "search",
+                SlashCommandPhase::Complete,
+                Duration::from_millis(millis),
+                1,
+                10,
+            );
+        }
+
+        let recent = metrics.recent_samples("search");
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].duration, Duration::from_millis(20));
+        assert_eq!(recent[1].duration, Duration::from_millis(30));
+
+        // Averages still reflect all three samples, not just the retained two.
+        let averages = metrics.averages("search").unwrap();
+        assert_eq!(averages.sample_count, 3);
+    }
+
+    #[test]
+    fn budget_cancels_once_byte_limit_exceeded() {
+        let budget = SlashCommandBudget::new(Duration::from_secs(60), Some(100));
+        let cancel = AtomicBool::new(false);
+
+        assert!(!budget.record_bytes(50, &cancel));
+        assert!(!cancel.load(Ordering::SeqCst));
+
+        assert!(budget.record_bytes(60, &cancel));
+        assert!(cancel.load(Ordering::SeqCst));
+    }
+}
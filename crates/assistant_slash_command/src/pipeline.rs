@@ -0,0 +1,56 @@
+//! Splitting a command line into pipeline stages on an unescaped `|`, e.g.
+//! `/diagnostics | /grep TODO` becomes two stages whose event streams the registry wires
+//! together so the right-hand stage's output is computed lazily from the left-hand stage's.
+
+/// Splits `input` on top-level `|` characters, treating `\|` as a literal pipe rather than a
+/// stage separator. Each returned stage has its surrounding whitespace trimmed.
+pub fn split_pipeline(input: &str) -> Vec<String> {
+    let mut stages = Vec::new();
+    let mut current = String::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if chars.peek() == Some(&'|') => {
+                current.push('|');
+                chars.next();
+            }
+            '|' => {
+                stages.push(std::mem::take(&mut current));
+            }
+            _ => current.push(ch),
+        }
+    }
+    stages.push(current);
+
+    stages
+        .into_iter()
+        .map(|stage| stage.trim().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_unescaped_pipe() {
+        assert_eq!(
+            split_pipeline("/diagnostics | /grep TODO"),
+            vec!["/diagnostics".to_string(), "/grep TODO".to_string()]
+        );
+    }
+
+    #[test]
+    fn preserves_escaped_pipe() {
+        assert_eq!(
+            split_pipeline(r"/search a \| b"),
+            vec![r"/search a | b".to_string()]
+        );
+    }
+
+    #[test]
+    fn single_stage_is_unchanged() {
+        assert_eq!(split_pipeline("/file src/main.rs"), vec!["/file src/main.rs".to_string()]);
+    }
+}
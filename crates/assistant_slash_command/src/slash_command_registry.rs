@@ -0,0 +1,597 @@
+//! The central lookup and dispatch point for [`SlashCommand`]s: resolves a command line (possibly
+//! a `|`-separated pipeline) to its command(s), walks a [`SlashCommand::command_node`] grammar
+//! when a command exposes one, and wraps every invocation in [`SlashCommandMetrics`].
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{atomic::AtomicBool, Arc},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use futures::{
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+use gpui::{AppContext, Global, Task, WeakView, WindowContext};
+use language::{BufferSnapshot, LspAdapterDelegate};
+use workspace::Workspace;
+
+use crate::{
+    command_tree,
+    metrics::{SlashCommandBudget, SlashCommandMetrics, SlashCommandPhase},
+    pipeline::split_pipeline,
+    ArgumentCompletion, SlashCommand, SlashCommandContent, SlashCommandEvent,
+    SlashCommandImageSource, SlashCommandOutputSection, SlashCommandResult,
+};
+
+struct GlobalSlashCommandRegistry(Arc<SlashCommandRegistry>);
+
+impl Global for GlobalSlashCommandRegistry {}
+
+/// The registry of all known [`SlashCommand`]s, stored as a [`gpui::Global`].
+#[derive(Default)]
+pub struct SlashCommandRegistry {
+    commands: HashMap<String, Arc<dyn SlashCommand>>,
+    metrics: Arc<SlashCommandMetrics>,
+}
+
+impl SlashCommandRegistry {
+    pub fn default_global(cx: &mut AppContext) -> Arc<Self> {
+        if !cx.has_global::<GlobalSlashCommandRegistry>() {
+            cx.set_global(GlobalSlashCommandRegistry(Arc::new(Self::default())));
+        }
+        cx.global::<GlobalSlashCommandRegistry>().0.clone()
+    }
+
+    pub fn global(cx: &AppContext) -> Arc<Self> {
+        cx.global::<GlobalSlashCommandRegistry>().0.clone()
+    }
+
+    pub fn register_command(&mut self, command: impl SlashCommand) {
+        self.commands.insert(command.name(), Arc::new(command));
+    }
+
+    pub fn command(&self, name: &str) -> Option<Arc<dyn SlashCommand>> {
+        self.commands.get(name).cloned()
+    }
+
+    pub fn command_names(&self) -> Vec<String> {
+        self.commands.keys().cloned().collect()
+    }
+
+    /// The execution metrics accumulated across every `complete_argument`/`run` this registry has
+    /// dispatched, e.g. for a `/metrics` diagnostic command.
+    pub fn metrics(&self) -> &Arc<SlashCommandMetrics> {
+        &self.metrics
+    }
+
+    /// Completes the argument at the cursor for command `name`. When the command exposes a
+    /// [`command_tree::CommandNode`] grammar, completions are derived by walking it against the
+    /// joined `arguments` instead of calling [`SlashCommand::complete_argument`].
+    pub fn complete_argument(
+        &self,
+        name: &str,
+        arguments: &[String],
+        cancel: Arc<AtomicBool>,
+        workspace: Option<WeakView<Workspace>>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Vec<ArgumentCompletion>>> {
+        let Some(command) = self.command(name) else {
+            return Task::ready(Err(anyhow!("no slash command named `/{name}`")));
+        };
+
+        if let Some(root) = command.command_node() {
+            let completions = self.metrics.time(name, SlashCommandPhase::Complete, || {
+                let completions = command_tree::complete(&root, &arguments.join(" "));
+                let count = completions.len();
+                (completions, count, 0)
+            });
+            return Task::ready(Ok(completions));
+        }
+
+        let metrics = self.metrics.clone();
+        let name = name.to_string();
+        let start = Instant::now();
+        let task = command.complete_argument(arguments, cancel, workspace, cx);
+
+        cx.foreground_executor().spawn(async move {
+            let result = task.await;
+            let count = result.as_ref().map_or(0, Vec::len);
+            metrics.record_since(&name, SlashCommandPhase::Complete, start, count, 0);
+            result
+        })
+    }
+
+    /// Runs `input`, which may be a `|`-separated pipeline, threading each stage's event stream
+    /// into the next stage's [`SlashCommand::run_piped`]. Each stage is resolved to its command
+    /// up front: an unknown command, or a non-first stage whose command doesn't
+    /// [`SlashCommand::accepts_input`], fails before anything runs. Each stage gets a fresh
+    /// `cancel` flag, tripped once its [`SlashCommand::budget`] (if any) is exceeded.
+    pub fn run(
+        &self,
+        input: &str,
+        context_slash_command_output_sections: &[SlashCommandOutputSection<language::Anchor>],
+        context_buffer: BufferSnapshot,
+        workspace: WeakView<Workspace>,
+        delegate: Option<Arc<dyn LspAdapterDelegate>>,
+        cx: &mut WindowContext,
+    ) -> Task<SlashCommandResult> {
+        let mut stages = match resolve_pipeline_stages(&self.commands, input) {
+            Ok(stages) => stages,
+            Err(error) => return Task::ready(Err(error)),
+        };
+
+        for stage in &mut stages {
+            if let Some(root) = stage.command.command_node() {
+                match command_tree::walk(&root, &stage.arguments.join(" ")) {
+                    Ok(context) => stage.parsed_arguments = Some(context),
+                    Err(error) => return Task::ready(Err(error.into())),
+                }
+            }
+        }
+
+        let sections = context_slash_command_output_sections.to_vec();
+        let metrics = self.metrics.clone();
+
+        cx.spawn(|mut cx| async move {
+            let mut upstream: Option<BoxStream<'static, Result<SlashCommandEvent>>> = None;
+
+            for stage in stages {
+                let name = stage.command.name();
+                let start = Instant::now();
+                let sections = sections.clone();
+                let context_buffer = context_buffer.clone();
+                let workspace = workspace.clone();
+                let delegate = delegate.clone();
+                let budget = stage.command.budget();
+                let cancel = Arc::new(AtomicBool::new(false));
+
+                let task = cx.update(|cx| {
+                    if let Some(input) = upstream.take() {
+                        stage.command.run_piped(
+                            input,
+                            &stage.arguments,
+                            stage.parsed_arguments,
+                            &sections,
+                            context_buffer,
+                            workspace,
+                            delegate,
+                            cancel.clone(),
+                            cx,
+                        )
+                    } else {
+                        stage.command.run(
+                            &stage.arguments,
+                            stage.parsed_arguments,
+                            &sections,
+                            context_buffer,
+                            workspace,
+                            delegate,
+                            cancel.clone(),
+                            cx,
+                        )
+                    }
+                })?;
+
+                let stream = task.await?;
+                upstream = Some(
+                    InstrumentedStream {
+                        inner: stream,
+                        name,
+                        metrics: metrics.clone(),
+                        start,
+                        event_count: 0,
+                        byte_len: 0,
+                        budget,
+                        cancel,
+                    }
+                    .boxed(),
+                );
+            }
+
+            Ok(upstream.unwrap_or_else(|| stream::empty().boxed()))
+        })
+    }
+}
+
+struct ResolvedStage {
+    command: Arc<dyn SlashCommand>,
+    arguments: Vec<String>,
+    parsed_arguments: Option<command_tree::CommandContext>,
+}
+
+impl std::fmt::Debug for ResolvedStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResolvedStage")
+            .field("command", &self.command.name())
+            .field("arguments", &self.arguments)
+            .finish()
+    }
+}
+
+/// Splits `input` into pipeline stages and resolves each to its command, erroring if a stage
+/// names an unknown command or if a non-first stage's command doesn't accept piped input.
+fn resolve_pipeline_stages(
+    commands: &HashMap<String, Arc<dyn SlashCommand>>,
+    input: &str,
+) -> Result<Vec<ResolvedStage>> {
+    split_pipeline(input)
+        .into_iter()
+        .enumerate()
+        .map(|(index, stage)| {
+            let mut parts = stage.split_whitespace();
+            let command_name = parts.next().unwrap_or_default().trim_start_matches('/');
+            let arguments = parts.map(str::to_string).collect();
+            let command = commands
+                .get(command_name)
+                .cloned()
+                .ok_or_else(|| anyhow!("no slash command named `/{command_name}`"))?;
+            if index > 0 && !command.accepts_input() {
+                return Err(anyhow!("`/{command_name}` does not accept piped input"));
+            }
+            Ok(ResolvedStage {
+                command,
+                arguments,
+                parsed_arguments: None,
+            })
+        })
+        .collect()
+}
+
+fn event_byte_len(event: &SlashCommandEvent) -> usize {
+    match event {
+        SlashCommandEvent::StartSection { label, .. } => label.len(),
+        SlashCommandEvent::Content(SlashCommandContent::Text { text, .. }) => text.len(),
+        SlashCommandEvent::Content(SlashCommandContent::Image { source, alt }) => {
+            alt.len()
+                + match source {
+                    SlashCommandImageSource::Data { data, .. } => data.len(),
+                    SlashCommandImageSource::Url(url) => url.len(),
+                }
+        }
+        SlashCommandEvent::Content(SlashCommandContent::Resource {
+            uri,
+            mime_type,
+            label,
+        }) => uri.len() + mime_type.len() + label.len(),
+        SlashCommandEvent::EndSection { .. } => 0,
+        SlashCommandEvent::Diagnostic { message, source, .. } => {
+            message.len() + source.as_ref().map_or(0, String::len)
+        }
+    }
+}
+
+/// Wraps a stage's event stream, tallying the events and bytes passing through so a
+/// [`crate::metrics::SlashCommandSample`] can be recorded when the stream is dropped (normally
+/// once it has been fully drained), rather than collecting it upfront (which would block a pipeline's downstream stage from
+/// starting until the upstream stage had entirely finished). When the command opted into a
+/// [`SlashCommandBudget`] (see [`SlashCommand::budget`]), each event's bytes are also charged
+/// against it, tripping `cancel` once the command's time/size limit is exceeded.
+struct InstrumentedStream<S> {
+    inner: S,
+    name: String,
+    metrics: Arc<SlashCommandMetrics>,
+    start: Instant,
+    event_count: usize,
+    byte_len: usize,
+    budget: Option<SlashCommandBudget>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl<S> Stream for InstrumentedStream<S>
+where
+    S: Stream<Item = Result<SlashCommandEvent>> + Unpin,
+{
+    type Item = Result<SlashCommandEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(Some(event)) => {
+                this.event_count += 1;
+                if let Ok(event) = &event {
+                    let byte_len = event_byte_len(event);
+                    this.byte_len += byte_len;
+                    if let Some(budget) = &this.budget {
+                        budget.record_bytes(byte_len, &this.cancel);
+                    }
+                }
+                Poll::Ready(Some(event))
+            }
+            other => other,
+        }
+    }
+}
+
+impl<S> Drop for InstrumentedStream<S> {
+    fn drop(&mut self) {
+        self.metrics.record_since(
+            &self.name,
+            SlashCommandPhase::Run,
+            self.start,
+            self.event_count,
+            self.byte_len,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_tree::{CommandNode, Enum, Integer};
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    struct StubCommand {
+        name: &'static str,
+        accepts_input: bool,
+    }
+
+    impl SlashCommand for StubCommand {
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn menu_text(&self) -> String {
+            String::new()
+        }
+
+        fn complete_argument(
+            self: Arc<Self>,
+            _arguments: &[String],
+            _cancel: Arc<AtomicBool>,
+            _workspace: Option<WeakView<Workspace>>,
+            _cx: &mut WindowContext,
+        ) -> Task<Result<Vec<ArgumentCompletion>>> {
+            unimplemented!("not exercised by these tests")
+        }
+
+        fn requires_argument(&self) -> bool {
+            false
+        }
+
+        fn run(
+            self: Arc<Self>,
+            _arguments: &[String],
+            _parsed_arguments: Option<command_tree::CommandContext>,
+            _context_slash_command_output_sections: &[SlashCommandOutputSection<language::Anchor>],
+            _context_buffer: BufferSnapshot,
+            _workspace: WeakView<Workspace>,
+            _delegate: Option<Arc<dyn LspAdapterDelegate>>,
+            _cancel: Arc<AtomicBool>,
+            _cx: &mut WindowContext,
+        ) -> Task<SlashCommandResult> {
+            unimplemented!("not exercised by these tests")
+        }
+
+        fn accepts_input(&self) -> bool {
+            self.accepts_input
+        }
+    }
+
+    fn stub(name: &'static str, accepts_input: bool) -> Arc<dyn SlashCommand> {
+        Arc::new(StubCommand {
+            name,
+            accepts_input,
+        })
+    }
+
+    /// A command exposing a [`command_tree::CommandNode`] grammar, so tests can exercise
+    /// `SlashCommandRegistry::complete_argument`'s command-node branch rather than only the free
+    /// functions it delegates to.
+    struct NodeCommand;
+
+    impl SlashCommand for NodeCommand {
+        fn name(&self) -> String {
+            "node".to_string()
+        }
+
+        fn description(&self) -> String {
+            String::new()
+        }
+
+        fn menu_text(&self) -> String {
+            String::new()
+        }
+
+        fn command_node(&self) -> Option<Arc<CommandNode>> {
+            Some(
+                CommandNode::literal("add")
+                    .then(CommandNode::argument("kind", Enum::new(["foo", "bar"])))
+                    .build(),
+            )
+        }
+
+        fn complete_argument(
+            self: Arc<Self>,
+            _arguments: &[String],
+            _cancel: Arc<AtomicBool>,
+            _workspace: Option<WeakView<Workspace>>,
+            _cx: &mut WindowContext,
+        ) -> Task<Result<Vec<ArgumentCompletion>>> {
+            unimplemented!("dispatched via command_node instead")
+        }
+
+        fn requires_argument(&self) -> bool {
+            false
+        }
+
+        fn run(
+            self: Arc<Self>,
+            _arguments: &[String],
+            _parsed_arguments: Option<command_tree::CommandContext>,
+            _context_slash_command_output_sections: &[SlashCommandOutputSection<language::Anchor>],
+            _context_buffer: BufferSnapshot,
+            _workspace: WeakView<Workspace>,
+            _delegate: Option<Arc<dyn LspAdapterDelegate>>,
+            _cancel: Arc<AtomicBool>,
+            _cx: &mut WindowContext,
+        ) -> Task<SlashCommandResult> {
+            unimplemented!("not exercised by these tests")
+        }
+    }
+
+    #[test]
+    fn resolves_single_stage() {
+        let commands = HashMap::from([("file".to_string(), stub("file", false))]);
+        let stages = resolve_pipeline_stages(&commands, "/file src/main.rs").unwrap();
+        assert_eq!(stages.len(), 1);
+        assert_eq!(stages[0].arguments, vec!["src/main.rs".to_string()]);
+    }
+
+    #[test]
+    fn resolves_pipeline_when_downstream_accepts_input() {
+        let commands = HashMap::from([
+            ("diagnostics".to_string(), stub("diagnostics", false)),
+            ("grep".to_string(), stub("grep", true)),
+        ]);
+        let stages = resolve_pipeline_stages(&commands, "/diagnostics | /grep TODO").unwrap();
+        assert_eq!(stages.len(), 2);
+        assert_eq!(stages[1].arguments, vec!["TODO".to_string()]);
+    }
+
+    #[test]
+    fn rejects_unknown_command() {
+        let commands = HashMap::new();
+        let error = resolve_pipeline_stages(&commands, "/nope").unwrap_err();
+        assert!(error.to_string().contains("nope"));
+    }
+
+    #[test]
+    fn rejects_pipe_into_command_that_does_not_accept_input() {
+        let commands = HashMap::from([
+            ("diagnostics".to_string(), stub("diagnostics", false)),
+            ("file".to_string(), stub("file", false)),
+        ]);
+        let error = resolve_pipeline_stages(&commands, "/diagnostics | /file a.rs").unwrap_err();
+        assert!(error.to_string().contains("does not accept piped input"));
+    }
+
+    #[test]
+    fn command_node_validates_arguments_before_run() {
+        let tree = CommandNode::literal("add")
+            .then(CommandNode::argument("count", Integer))
+            .build();
+
+        let context = command_tree::walk(&tree, "add 3").unwrap();
+        assert_eq!(context.get::<i64>("count"), Some(&3));
+
+        let error = command_tree::walk(&tree, "add NaN").unwrap_err();
+        assert!(error.to_string().contains("integer"));
+    }
+
+    #[test]
+    fn instrumented_stream_records_metrics_once_drained() {
+        let metrics = Arc::new(SlashCommandMetrics::new(10));
+        let events = vec![
+            Ok(SlashCommandEvent::Content(SlashCommandContent::Text {
+                text: "hello".to_string(),
+                run_commands_in_text: false,
+            })),
+            Ok(SlashCommandEvent::Content(SlashCommandContent::Text {
+                text: "world".to_string(),
+                run_commands_in_text: false,
+            })),
+        ];
+
+        let stream = InstrumentedStream {
+            inner: stream::iter(events).boxed(),
+            name: "fetch".to_string(),
+            metrics: metrics.clone(),
+            start: Instant::now(),
+            event_count: 0,
+            byte_len: 0,
+            budget: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        };
+
+        let collected: Vec<_> = futures::executor::block_on(stream.collect());
+        assert_eq!(collected.len(), 2);
+
+        let averages = metrics.averages("fetch").unwrap();
+        assert_eq!(averages.sample_count, 1);
+        assert_eq!(averages.average_event_count, 2.0);
+        assert_eq!(averages.average_byte_len, 10.0);
+    }
+
+    #[test]
+    fn instrumented_stream_cancels_once_budget_exceeded() {
+        let metrics = Arc::new(SlashCommandMetrics::new(10));
+        let events = vec![
+            Ok(SlashCommandEvent::Content(SlashCommandContent::Text {
+                text: "hello".to_string(),
+                run_commands_in_text: false,
+            })),
+            Ok(SlashCommandEvent::Content(SlashCommandContent::Text {
+                text: "world".to_string(),
+                run_commands_in_text: false,
+            })),
+        ];
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let stream = InstrumentedStream {
+            inner: stream::iter(events).boxed(),
+            name: "fetch".to_string(),
+            metrics,
+            start: Instant::now(),
+            event_count: 0,
+            byte_len: 0,
+            budget: Some(SlashCommandBudget::new(Duration::from_secs(60), Some(5))),
+            cancel: cancel.clone(),
+        };
+
+        let collected: Vec<_> = futures::executor::block_on(stream.collect());
+        assert_eq!(collected.len(), 2);
+        assert!(cancel.load(Ordering::SeqCst));
+    }
+
+    /// Exercises `SlashCommandRegistry::complete_argument`'s actual dispatch, rather than just
+    /// `command_tree::complete` in isolation, so a regression in how the registry derives
+    /// `after_completion` for a command-node leaf (see `command_tree.rs`) would fail here too.
+    #[gpui::test]
+    async fn complete_argument_dispatches_through_command_node(cx: &mut gpui::TestAppContext) {
+        let mut registry = SlashCommandRegistry::default();
+        registry.register_command(NodeCommand);
+        let registry = Arc::new(registry);
+
+        let window = cx.add_window(|_cx| gpui::Empty);
+        let completions = window
+            .update(cx, |_, cx| {
+                registry.complete_argument(
+                    "node",
+                    &["add".to_string(), "f".to_string()],
+                    Arc::new(AtomicBool::new(false)),
+                    None,
+                    cx,
+                )
+            })
+            .unwrap()
+            .await
+            .unwrap();
+
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].new_text, "foo");
+        assert_eq!(completions[0].after_completion, crate::AfterCompletion::Run);
+
+        let error = window
+            .update(cx, |_, cx| {
+                registry.complete_argument(
+                    "not-a-command",
+                    &[],
+                    Arc::new(AtomicBool::new(false)),
+                    None,
+                    cx,
+                )
+            })
+            .unwrap()
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not-a-command"));
+    }
+}